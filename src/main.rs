@@ -20,15 +20,20 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 
 use heliocron::{calc, config, errors, structs, enums, subcommands};
-use chrono::{DateTime, Duration, FixedOffset, Local, TimeZone};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, Offset, TimeZone};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Result};
 use std::error::Error;
 use std::fs::OpenOptions;
-use std::io::{self, Write};
-use std::process::Command;
+use std::io;
 use structopt::StructOpt;
 
+mod output;
+mod spa;
+use output::OutputFormat;
+use spa::SunriseAndSet;
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "hal")]
 struct Opt {
@@ -69,69 +74,228 @@ struct Opt {
         default_value = "-74.0060"
     )]
     longitude: f64,
+
+    #[structopt(
+        long = "azimuth",
+        help = "Report the sun's azimuth and elevation instead of sunrise/sunset"
+    )]
+    azimuth: bool,
+
+    #[structopt(
+        long = "at",
+        help = "Time of day to compute the sun's position for, HH:MM:SS (used with --azimuth, defaults to 12:00 local time on the resolved day)",
+        requires = "azimuth"
+    )]
+    at: Option<String>,
+
+    #[structopt(
+        long = "events",
+        help = "Comma-separated list of events to report: sunrise, sunset, civil_dawn, civil_dusk, nautical_dawn, nautical_dusk, astronomical_dawn, astronomical_dusk",
+        default_value = "sunrise,sunset",
+        use_delimiter = true
+    )]
+    events: Vec<String>,
+
+    #[structopt(
+        long = "date",
+        help = "Date to compute for, YYYY-MM-DD (defaults to today)"
+    )]
+    date: Option<String>,
+
+    #[structopt(
+        long = "timezone",
+        help = "IANA time zone name, e.g. America/New_York (defaults to the local time zone)"
+    )]
+    timezone: Option<String>,
+
+    #[structopt(
+        long = "format",
+        help = "Output format: json, text, csv or ical",
+        default_value = "json"
+    )]
+    format: OutputFormat,
+
+    #[structopt(
+        long = "from",
+        help = "Start of a date range to batch over, YYYY-MM-DD (requires --to)",
+        requires = "to"
+    )]
+    from: Option<String>,
+
+    #[structopt(
+        long = "to",
+        help = "End (inclusive) of a date range to batch over, YYYY-MM-DD",
+        requires = "from"
+    )]
+    to: Option<String>,
+
+    #[structopt(
+        long = "step",
+        help = "Number of days to advance between entries in a --from/--to batch",
+        default_value = "1"
+    )]
+    step: i64,
 }
 
-/*
-fn invoke_heliocron_report(
-    date: &str,
-    timezone: &str,
-    latitude: &str,
-    longitude: &str,
-    verbose: bool,
-) -> (String, String) {
-    let mut sunrise_sunset = ("".to_string(), "".to_string());
-    let report = Command::new("heliocron")
-        .arg("--date")
-        .arg(&date)
-        .arg("--latitude")
-        .arg(&latitude)
-        .arg("--longitude")
-        .arg(&longitude)
-        .arg("--time-zone")
-        .arg(&timezone)
-        .arg("report")
-        .output()
-        .expect("failed to execute process");
-
-    if verbose {
-        println!(
-            "heliocron {} {} {} {} {} {} {} {} {}",
-            "--date",
-            &date,
-            "--latitude",
-            &latitude,
-            "--longitude",
-            &longitude,
-            "--time-zone",
-            &timezone,
-            "report"
-        );
+/// Events `hal` knows how to compute, i.e. the ones `enums::Event::new`
+/// accepts. Checked up front so a typo in `--events` produces a clean
+/// `io::Error` instead of panicking deep inside `compute_events`.
+const KNOWN_EVENTS: &[&str] = &[
+    "sunrise",
+    "sunset",
+    "civil_dawn",
+    "civil_dusk",
+    "nautical_dawn",
+    "nautical_dusk",
+    "astronomical_dawn",
+    "astronomical_dusk",
+];
+
+fn validate_events(events: &[String]) -> io::Result<()> {
+    for event in events {
+        if !KNOWN_EVENTS.contains(&event.as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown event '{}', expected one of: {}", event, KNOWN_EVENTS.join(", ")),
+            ));
+        }
     }
+    Ok(())
+}
+
+/// Compute the requested events for a single day, handling polar day/night
+/// for sunrise/sunset the same way a single-day run does.
+fn compute_events(
+    date: DateTime<FixedOffset>,
+    coordinates: structs::Coordinates,
+    requested_events: &[String],
+    verbose: u8,
+) -> Vec<output::Event> {
+    let solar_calculations = calc::SolarCalculations::new(date, coordinates);
+    let calc = |op: &str| solar_calculations.calculate_event_time(enums::Event::new(op, None).unwrap());
+
+    let mut events: Vec<output::Event> = Vec::new();
+
+    let wants_sunrise = requested_events.iter().any(|e| e == "sunrise");
+    let wants_sunset = requested_events.iter().any(|e| e == "sunset");
+    if wants_sunrise || wants_sunset {
+        let sunrise = calc("sunrise");
+        let sunset = calc("sunset");
+        let condition = spa::classify_day(&sunrise, &sunset, date, &coordinates);
 
-    if report.status.success() {
-        let to_parse = String::from_utf8_lossy(&report.stdout);
-        let lines = to_parse.lines();
-        for line in lines {
-            let extract_time = |s: &str| {
-                let vec = s.split_whitespace().collect::<Vec<&str>>();
-                // Sunrise is at:            2022-01-22 10:51:47 +03:00
-                // 0       1  2              3          4
-                let time = String::from(vec[4]);
-                time
-            };
-            if line.starts_with("Sunrise is at:") {
-                sunrise_sunset.0 = extract_time(&line);
+        if verbose > 0 {
+            match condition {
+                SunriseAndSet::Normal { sunrise, sunset } => {
+                    println!("sunrise: {} sunset: {}", sunrise.time(), sunset.time())
+                }
+                SunriseAndSet::PolarDay => println!("condition: polar day (sun does not set)"),
+                SunriseAndSet::PolarNight => println!("condition: polar night (sun does not rise)"),
             }
-            if line.starts_with("Sunset is at:") {
-                sunrise_sunset.1 = extract_time(&line);
+        }
+
+        match condition {
+            SunriseAndSet::Normal { sunrise, sunset } => {
+                if wants_sunrise {
+                    events.push(("day_start".to_string(), Some(format!("{}", sunrise.time()))));
+                }
+                if wants_sunset {
+                    events.push(("day_end".to_string(), Some(format!("{}", sunset.time()))));
+                }
+            }
+            SunriseAndSet::PolarDay => events.push(("condition".to_string(), Some("polar_day".to_string()))),
+            SunriseAndSet::PolarNight => {
+                events.push(("condition".to_string(), Some("polar_night".to_string())))
             }
         }
-    } else {
-        io::stderr().write_all(&report.stderr).unwrap();
     }
-    sunrise_sunset
+
+    for event in requested_events.iter().filter(|e| e.as_str() != "sunrise" && e.as_str() != "sunset") {
+        let event_time = calc(event);
+        let value = event_time.datetime.map(|dt| format!("{}", dt.time()));
+        if verbose > 0 {
+            println!("{}: {:?}", event, value);
+        }
+        events.push((event_json_key(event).to_string(), value));
+    }
+
+    events
+}
+
+/// Resolve the `--date`/`--timezone` options to a `DateTime<FixedOffset>` at
+/// 12:00 local clock time, the instant the rest of `hal` computes
+/// sunrise/sunset and solar position relative to. Falls back to today in the local time
+/// zone when either option is omitted.
+fn resolve_date(date: &Option<String>, timezone: &Option<String>) -> io::Result<DateTime<FixedOffset>> {
+    let naive_date = match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        None => Local::today().naive_local(),
+    };
+    let noon = naive_date.and_hms(12, 0, 0);
+
+    match timezone {
+        Some(name) => {
+            let tz: Tz = name
+                .parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown time zone: {}", name)))?;
+            let localized = tz.from_local_datetime(&noon).single().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "ambiguous or non-existent local time")
+            })?;
+            Ok(localized.with_timezone(&localized.offset().fix()))
+        }
+        None => {
+            let localized = Local.from_local_datetime(&noon).single().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "ambiguous or non-existent local time")
+            })?;
+            Ok(localized.with_timezone(&FixedOffset::from_offset(localized.offset())))
+        }
+    }
+}
+
+/// Key used for an event in the output JSON: sunrise/sunset keep their
+/// established `day_start`/`day_end` names for backwards compatibility,
+/// everything else is reported under its own event name.
+fn event_json_key(event: &str) -> &str {
+    match event {
+        "sunrise" => "day_start",
+        "sunset" => "day_end",
+        other => other,
+    }
+}
+
+/// Compute sunrise/sunset (as `HH:MM:SS` strings) for a date, IANA time
+/// zone and location. Used by `main()`'s tests; superseded the old
+/// `invoke_heliocron_report`, which shelled out to a separate `heliocron`
+/// binary, now that `hal` resolves dates and time zones itself.
+fn compute_sunrise_sunset(
+    date: &str,
+    timezone: &str,
+    latitude: f64,
+    longitude: f64,
+) -> io::Result<(String, String)> {
+    let config_date = resolve_date(&Some(date.to_string()), &Some(timezone.to_string()))?;
+    let coordinates = structs::Coordinates {
+        latitude: structs::Latitude { value: latitude },
+        longitude: structs::Longitude { value: longitude },
+    };
+    let solar_calculations = calc::SolarCalculations::new(config_date, coordinates);
+    let sunrise = solar_calculations.calculate_event_time(enums::Event::new("sunrise", None).unwrap());
+    let sunset = solar_calculations.calculate_event_time(enums::Event::new("sunset", None).unwrap());
+
+    Ok((
+        sunrise.datetime.unwrap().format("%H:%M:%S").to_string(),
+        sunset.datetime.unwrap().format("%H:%M:%S").to_string(),
+    ))
+}
+
+/// Build a fresh `Coordinates` from the `--latitude`/`--longitude` options;
+/// batch mode calls this once per day.
+fn coordinates(opt: &Opt) -> structs::Coordinates {
+    structs::Coordinates {
+        latitude: structs::Latitude { value: opt.latitude },
+        longitude: structs::Longitude { value: opt.longitude },
+    }
 }
-*/
 
 fn main() -> io::Result<()> {
     let opt = Opt::from_args();
@@ -140,49 +304,92 @@ fn main() -> io::Result<()> {
         println!("time_offset {}", opt.time_offset);
     }
 
-    let (date, longitude, latitude, timezone): (String, String, String, String);
+    validate_events(&opt.events)?;
+
+    // `--azimuth` always reports a single azimuth/elevation pair as JSON; it
+    // doesn't fit the per-event `--format text/csv/ical` shapes, so reject the
+    // combination instead of silently ignoring `--format`.
+    if opt.azimuth && opt.format != OutputFormat::Json {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--format is not supported with --azimuth; azimuth output is always JSON",
+        ));
+    }
 
     let config = config::Config {
-        coordinates: structs::Coordinates {
-            latitude: structs::Latitude { value: opt.latitude },
-            longitude: structs::Longitude { value: opt.longitude },
-        },
-        date: Local::today()
-        .and_hms(12, 0, 0)
-        .with_timezone(&FixedOffset::from_offset(Local::today().offset())),
+        coordinates: coordinates(&opt),
+        date: resolve_date(&opt.date, &opt.timezone)?,
         action: config::Action::Report,
     };
 
-    let solar_calculations = calc::SolarCalculations::new(config.date, config.coordinates);
-    let calc = |op: &str|
-    {
-        solar_calculations.calculate_event_time(enums::Event::new(op, None).unwrap())
-    };
-    let sunrise = calc("sunrise");
-    let sunset = calc("sunset");
+    if opt.azimuth {
+        let instant = match &opt.at {
+            Some(at) => {
+                let time = chrono::NaiveTime::parse_from_str(at, "%H:%M:%S")
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                config.date.date().and_time(time).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "non-existent local time for --at")
+                })?
+            }
+            None => config.date,
+        };
 
-    let just_time = |ev: &structs::EventTime| { ev.datetime.unwrap().time() };
+        let position = spa::solar_position(instant, &config.coordinates);
+        let position_json = json!({ "azimuth": position.azimuth, "elevation": position.elevation });
 
-    if opt.verbose > 0 {
-        println!("sunrise: {} sunset: {}", just_time(&sunrise), just_time(&sunset));
+        if opt.verbose > 0 {
+            println!(
+                "azimuth: {:.4} elevation: {:.4}",
+                position.azimuth, position.elevation
+            );
+        }
+
+        return match opt.out {
+            None => {
+                println!("{:}", position_json.to_string());
+                Ok(())
+            }
+            Some(oname) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(oname)?;
+                serde_json::to_writer(&file, &position_json)?;
+                Ok(())
+            }
+        };
     }
 
-    let sunrise_sunset_json = json!({ "day_start" : format!("{}", just_time(&sunrise)),
-                                            "day_end" : format!("{}", just_time(&sunset)) });
-
-    match opt.out {
-        None => println!("{:}", sunrise_sunset_json.to_string()),
-        Some(oname) => {
-            let file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(oname)?;
-            serde_json::to_writer(&file, &sunrise_sunset_json)?;
+    if let (Some(from), Some(to)) = (&opt.from, &opt.to) {
+        if opt.step < 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--step must be at least 1"));
+        }
+        let from = NaiveDate::parse_from_str(from, "%Y-%m-%d")
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let to = NaiveDate::parse_from_str(to, "%Y-%m-%d")
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let mut writer = output::BatchWriter::new(opt.format, opt.out.as_deref())?;
+        let mut current = from;
+        while current <= to {
+            // Re-resolve the offset for each date rather than reusing the one
+            // `config.date` picked up: a zone that observes DST (e.g.
+            // `America/New_York`) has a different UTC offset on opposite sides
+            // of the range, so a single hoisted offset would be wrong for half
+            // of a year-spanning batch.
+            let date = resolve_date(&Some(current.format("%Y-%m-%d").to_string()), &opt.timezone)?;
+            let events = compute_events(date, coordinates(&opt), &opt.events, opt.verbose);
+            writer.write_day(date, &events)?;
+            current += Duration::days(opt.step);
         }
+
+        return writer.finish();
     }
 
-    Ok(())
+    let events = compute_events(config.date, coordinates(&opt), &opt.events, opt.verbose);
+
+    output::write_events(opt.format, &events, config.date, opt.out.as_deref())
 }
 
 #[cfg(test)]
@@ -192,32 +399,42 @@ mod tests {
     #[test]
     fn test_nyc_sunrise_sunset() {
         // NYC 40.7128° N, 74.0060° W
-        /*
-        let (sunrise, sunset) = invoke_heliocron_report(
-            "2022-01-24",
-            "-05:00",   // TZ offset of NYC: GMT-5
-            "40.7128N", // latitude of NYC
-            "74.0060W", // longitude of NYC
-            false,
-        ); // be silent
+        let (sunrise, sunset) =
+            compute_sunrise_sunset("2022-01-24", "America/New_York", 40.7128, -74.0060).unwrap();
         assert_eq!(sunrise, "07:12:36");
         assert_eq!(sunset, "17:03:42");
-        */
     }
 
     #[test]
     fn test_ok_sunrise_sunset() {
         // Oakland, CA 37.8044° N, 122.2712° W
-        /*
-        let (sunrise, sunset) = invoke_heliocron_report(
-            "2022-01-25",
-            "-08:00",    // TZ offset of Oakland, CA: GMT-8
-            "37.8044N",  // latitude of Oakland, CA
-            "122.2712W", // longitude of Oakland, CA
-            false,
-        ); // be silent
+        let (sunrise, sunset) =
+            compute_sunrise_sunset("2022-01-25", "America/Los_Angeles", 37.8044, -122.2712).unwrap();
         assert_eq!(sunrise, "07:18:10");
         assert_eq!(sunset, "17:24:46");
-        */
+    }
+
+    #[test]
+    fn test_validate_events_rejects_unknown_event() {
+        let err = validate_events(&["sunrise".to_string(), "not_an_event".to_string()]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_compute_events_selects_civil_twilight() {
+        let date = resolve_date(&Some("2022-01-24".to_string()), &Some("America/New_York".to_string())).unwrap();
+        let coordinates = structs::Coordinates {
+            latitude: structs::Latitude { value: 40.7128 },
+            longitude: structs::Longitude { value: -74.0060 },
+        };
+        let events = compute_events(
+            date,
+            coordinates,
+            &["civil_dawn".to_string(), "civil_dusk".to_string()],
+            0,
+        );
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["civil_dawn", "civil_dusk"]);
+        assert!(events.iter().all(|(_, time)| time.is_some()));
     }
 }
@@ -0,0 +1,335 @@
+// Output formats for the events `hal` computes, borrowing the
+// encode/decode-by-format design from the `ilc` converter: one small
+// trait implemented per format, selected by a `--format` flag.
+
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeZone, Utc};
+use std::fs::File;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// An event name paired with its computed time, or `None` when the event
+/// doesn't occur on the given day (e.g. sunrise during polar night).
+pub type Event = (String, Option<String>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Text,
+    Csv,
+    Ical,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "text" => Ok(OutputFormat::Text),
+            "csv" => Ok(OutputFormat::Csv),
+            "ical" => Ok(OutputFormat::Ical),
+            other => Err(format!("unknown output format: {}", other)),
+        }
+    }
+}
+
+/// Serializes a set of computed events to a specific output format.
+///
+/// A batch (`--from`/`--to`) run never holds more than one day's events in
+/// memory: `batch_header`/`batch_day`/`batch_footer` let `BatchWriter` stream
+/// each day straight to its sink as it's computed, rather than collecting
+/// the whole range into one buffer before writing anything.
+pub trait EventSerializer {
+    fn serialize(&self, events: &[Event], date: DateTime<FixedOffset>) -> String;
+
+    /// Emitted once, before the first day of a batch.
+    fn batch_header(&self) -> String {
+        String::new()
+    }
+
+    /// Emitted once per day of a batch, `index` counting from zero.
+    fn batch_day(&self, index: usize, date: DateTime<FixedOffset>, events: &[Event]) -> String;
+
+    /// Emitted once, after the last day of a batch.
+    fn batch_footer(&self) -> String {
+        String::new()
+    }
+}
+
+pub struct JsonSerializer;
+
+impl JsonSerializer {
+    fn as_object(events: &[Event]) -> serde_json::Map<String, serde_json::Value> {
+        let mut map = serde_json::Map::new();
+        for (name, time) in events {
+            let value = match time {
+                Some(time) => serde_json::json!(time),
+                None => serde_json::Value::Null,
+            };
+            map.insert(name.clone(), value);
+        }
+        map
+    }
+}
+
+impl EventSerializer for JsonSerializer {
+    fn serialize(&self, events: &[Event], _date: DateTime<FixedOffset>) -> String {
+        serde_json::Value::Object(Self::as_object(events)).to_string()
+    }
+
+    fn batch_header(&self) -> String {
+        "[".to_string()
+    }
+
+    fn batch_day(&self, index: usize, date: DateTime<FixedOffset>, events: &[Event]) -> String {
+        let mut map = Self::as_object(events);
+        map.insert("date".to_string(), serde_json::json!(format!("{}", date.date())));
+        let separator = if index == 0 { "" } else { "," };
+        format!("{}{}", separator, serde_json::Value::Object(map))
+    }
+
+    fn batch_footer(&self) -> String {
+        "]".to_string()
+    }
+}
+
+pub struct TextSerializer;
+
+impl EventSerializer for TextSerializer {
+    fn serialize(&self, events: &[Event], date: DateTime<FixedOffset>) -> String {
+        let mut report = format!("Report for {}\n", date.format("%Y-%m-%d"));
+        for (name, time) in events {
+            match time {
+                Some(time) => report.push_str(&format!("{}: {}\n", name, time)),
+                None => report.push_str(&format!("{}: does not occur\n", name)),
+            }
+        }
+        report
+    }
+
+    fn batch_day(&self, index: usize, date: DateTime<FixedOffset>, events: &[Event]) -> String {
+        let separator = if index == 0 { "" } else { "\n" };
+        format!("{}{}", separator, self.serialize(events, date))
+    }
+}
+
+pub struct CsvSerializer;
+
+impl EventSerializer for CsvSerializer {
+    fn serialize(&self, events: &[Event], _date: DateTime<FixedOffset>) -> String {
+        let mut csv = String::from("event,time\n");
+        for (name, time) in events {
+            csv.push_str(&format!("{},{}\n", name, time.as_deref().unwrap_or("")));
+        }
+        csv
+    }
+
+    fn batch_header(&self) -> String {
+        "date,event,time\n".to_string()
+    }
+
+    fn batch_day(&self, _index: usize, date: DateTime<FixedOffset>, events: &[Event]) -> String {
+        let mut csv = String::new();
+        for (name, time) in events {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                date.date(),
+                name,
+                time.as_deref().unwrap_or("")
+            ));
+        }
+        csv
+    }
+}
+
+pub struct IcalSerializer;
+
+impl IcalSerializer {
+    /// Build a UTC `DTSTART` (`Z`-suffixed) for an event's `HH:MM:SS` time on
+    /// the given day, so the emitted instant is anchored to the zone `--date`
+    /// / `--timezone` resolved rather than left as floating local time.
+    fn dtstart(time: &str, date: DateTime<FixedOffset>) -> Option<String> {
+        let time = NaiveTime::parse_from_str(time, "%H:%M:%S").ok()?;
+        let local = date.offset().from_local_datetime(&date.date().naive_local().and_time(time)).single()?;
+        Some(local.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string())
+    }
+
+    fn vevents(events: &[Event], date: DateTime<FixedOffset>) -> String {
+        let mut vevents = String::new();
+        for (name, time) in events {
+            let time = match time {
+                Some(time) => time,
+                None => continue,
+            };
+            let dtstart = match Self::dtstart(time, date) {
+                Some(dtstart) => dtstart,
+                None => continue,
+            };
+            vevents.push_str("BEGIN:VEVENT\r\n");
+            vevents.push_str(&format!("SUMMARY:{}\r\n", name));
+            vevents.push_str(&format!("DTSTART:{}\r\n", dtstart));
+            vevents.push_str("END:VEVENT\r\n");
+        }
+        vevents
+    }
+}
+
+const ICAL_HEADER: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//hal//sunrise-sunset//EN\r\n";
+const ICAL_FOOTER: &str = "END:VCALENDAR\r\n";
+
+impl EventSerializer for IcalSerializer {
+    fn serialize(&self, events: &[Event], date: DateTime<FixedOffset>) -> String {
+        format!("{}{}{}", ICAL_HEADER, Self::vevents(events, date), ICAL_FOOTER)
+    }
+
+    fn batch_header(&self) -> String {
+        ICAL_HEADER.to_string()
+    }
+
+    fn batch_day(&self, _index: usize, date: DateTime<FixedOffset>, events: &[Event]) -> String {
+        Self::vevents(events, date)
+    }
+
+    fn batch_footer(&self) -> String {
+        ICAL_FOOTER.to_string()
+    }
+}
+
+impl OutputFormat {
+    pub fn serializer(&self) -> Box<dyn EventSerializer> {
+        match self {
+            OutputFormat::Json => Box::new(JsonSerializer),
+            OutputFormat::Text => Box::new(TextSerializer),
+            OutputFormat::Csv => Box::new(CsvSerializer),
+            OutputFormat::Ical => Box::new(IcalSerializer),
+        }
+    }
+}
+
+pub fn write_events(
+    format: OutputFormat,
+    events: &[Event],
+    date: DateTime<FixedOffset>,
+    out: Option<&str>,
+) -> io::Result<()> {
+    let body = format.serializer().serialize(events, date);
+    match out {
+        None => println!("{}", body),
+        Some(path) => std::fs::write(path, body)?,
+    }
+    Ok(())
+}
+
+/// Streams a `--from`/`--to` batch of days to `out` (stdout, or a file when
+/// given) one day at a time, so a long date range never holds more than a
+/// single day's serialized events in memory.
+pub struct BatchWriter {
+    serializer: Box<dyn EventSerializer>,
+    sink: Box<dyn Write>,
+    index: usize,
+}
+
+impl BatchWriter {
+    pub fn new(format: OutputFormat, out: Option<&str>) -> io::Result<Self> {
+        let sink: Box<dyn Write> = match out {
+            None => Box::new(io::stdout()),
+            Some(path) => Box::new(File::create(path)?),
+        };
+        let mut writer = BatchWriter {
+            serializer: format.serializer(),
+            sink,
+            index: 0,
+        };
+        let header = writer.serializer.batch_header();
+        writer.sink.write_all(header.as_bytes())?;
+        Ok(writer)
+    }
+
+    pub fn write_day(&mut self, date: DateTime<FixedOffset>, events: &[Event]) -> io::Result<()> {
+        let chunk = self.serializer.batch_day(self.index, date, events);
+        self.sink.write_all(chunk.as_bytes())?;
+        self.index += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        let footer = self.serializer.batch_footer();
+        self.sink.write_all(footer.as_bytes())?;
+        self.sink.write_all(b"\n")?;
+        self.sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample_date() -> DateTime<FixedOffset> {
+        FixedOffset::east(0).ymd(2022, 1, 24).and_hms(12, 0, 0)
+    }
+
+    fn sample_events() -> Vec<Event> {
+        vec![
+            ("day_start".to_string(), Some("07:12:36".to_string())),
+            ("day_end".to_string(), Some("17:03:42".to_string())),
+        ]
+    }
+
+    #[test]
+    fn test_json_serializer() {
+        let body = JsonSerializer.serialize(&sample_events(), sample_date());
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["day_start"], "07:12:36");
+        assert_eq!(value["day_end"], "17:03:42");
+    }
+
+    #[test]
+    fn test_text_serializer() {
+        let body = TextSerializer.serialize(&sample_events(), sample_date());
+        assert_eq!(
+            body,
+            "Report for 2022-01-24\nday_start: 07:12:36\nday_end: 17:03:42\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_serializer() {
+        let body = CsvSerializer.serialize(&sample_events(), sample_date());
+        assert_eq!(body, "event,time\nday_start,07:12:36\nday_end,17:03:42\n");
+    }
+
+    #[test]
+    fn test_ical_serializer() {
+        let body = IcalSerializer.serialize(&sample_events(), sample_date());
+        assert!(body.starts_with("BEGIN:VCALENDAR"));
+        assert!(body.contains("SUMMARY:day_start"));
+        assert!(body.contains("DTSTART:20220124T071236Z"));
+        assert!(body.trim_end().ends_with("END:VCALENDAR"));
+    }
+
+    // Batch (--from/--to) mode never builds the whole body at once: it writes
+    // batch_header/batch_day/batch_footer straight to the sink as each day is
+    // computed. These tests assemble that same sequence by hand to check the
+    // result is well-formed without needing a real file or stdout sink.
+    #[test]
+    fn test_json_batch_streaming_produces_an_array() {
+        let serializer = JsonSerializer;
+        let mut body = serializer.batch_header();
+        body.push_str(&serializer.batch_day(0, sample_date(), &sample_events()));
+        body.push_str(&serializer.batch_day(1, sample_date(), &sample_events()));
+        body.push_str(&serializer.batch_footer());
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_csv_batch_streaming_has_one_header_and_one_row_per_event_per_day() {
+        let serializer = CsvSerializer;
+        let mut body = serializer.batch_header();
+        body.push_str(&serializer.batch_day(0, sample_date(), &sample_events()));
+        body.push_str(&serializer.batch_day(1, sample_date(), &sample_events()));
+        assert_eq!(body.matches("date,event,time").count(), 1);
+        assert_eq!(body.matches("day_start").count(), 2);
+    }
+}
@@ -0,0 +1,194 @@
+// Minimal reimplementation of the parts of the external SPA (Solar Position
+// Algorithm) crate that `hal` needs. heliocron only exposes sunrise/sunset
+// event times; at high latitudes those events don't exist on every day, so
+// we fall back to checking the sun's elevation at local solar noon to tell
+// polar day from polar night.
+
+use chrono::{DateTime, Datelike, FixedOffset, Utc};
+use heliocron::structs::Coordinates;
+
+/// Degrees-to-radians, matching the constant used by the SPA reference
+/// implementation.
+pub const DEG_TO_RAD: f64 = 0.0174533;
+
+/// Julian date of the J2000.0 epoch, the reference instant the SPA
+/// algorithm measures everything relative to.
+pub const JD2000: f64 = 2451545.0;
+
+/// Mean radius of the Earth, in kilometres.
+pub const EARTH_MEAN_RADIUS: f64 = 6371.01;
+
+/// One astronomical unit, in kilometres.
+pub const ASTRONOMICAL_UNIT: f64 = 149_597_890.0;
+
+/// Parallax correction applied to the computed elevation: the angle
+/// subtended by the Earth's mean radius as seen from the sun, i.e.
+/// `asin(EARTH_MEAN_RADIUS / ASTRONOMICAL_UNIT)`, computed once here since
+/// `asin` isn't usable in a const context.
+pub fn parallax_correction_rad() -> f64 {
+    (EARTH_MEAN_RADIUS / ASTRONOMICAL_UNIT).asin()
+}
+
+/// The sun's position as seen from a given point on Earth at a given
+/// instant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolarPosition {
+    /// Azimuth, in degrees measured clockwise from north.
+    pub azimuth: f64,
+    /// Elevation above the horizon, in degrees (negative if below).
+    pub elevation: f64,
+}
+
+fn julian_date(date: DateTime<FixedOffset>) -> f64 {
+    let utc = date.with_timezone(&Utc);
+    utc.timestamp() as f64 / 86400.0 + 2440587.5
+}
+
+/// Compute the sun's azimuth and elevation at `date` for the given
+/// coordinates, following the SPA algorithm: Julian date relative to
+/// JD2000, ecliptic longitude and the sun's right ascension/declination,
+/// local hour angle from the observer's longitude and sidereal time, then
+/// projection to horizontal coordinates.
+pub fn solar_position(date: DateTime<FixedOffset>, coordinates: &Coordinates) -> SolarPosition {
+    let d = julian_date(date) - JD2000;
+
+    let mean_longitude = (280.460 + 0.9856474 * d).rem_euclid(360.0);
+    let mean_anomaly_rad = (357.528 + 0.9856003 * d).rem_euclid(360.0) * DEG_TO_RAD;
+
+    let ecliptic_longitude_rad = (mean_longitude
+        + 1.915 * mean_anomaly_rad.sin()
+        + 0.020 * (2.0 * mean_anomaly_rad).sin())
+        * DEG_TO_RAD;
+
+    let obliquity_rad = (23.439 - 0.0000004 * d) * DEG_TO_RAD;
+
+    let right_ascension_deg = (obliquity_rad.cos() * ecliptic_longitude_rad.sin())
+        .atan2(ecliptic_longitude_rad.cos())
+        / DEG_TO_RAD;
+    let declination_rad = (obliquity_rad.sin() * ecliptic_longitude_rad.sin()).asin();
+
+    let greenwich_sidereal_time = (280.46061837 + 360.98564736629 * d).rem_euclid(360.0);
+    let local_sidereal_time =
+        (greenwich_sidereal_time + coordinates.longitude.value).rem_euclid(360.0);
+    let hour_angle_rad =
+        (local_sidereal_time - right_ascension_deg).rem_euclid(360.0) * DEG_TO_RAD;
+
+    let latitude_rad = coordinates.latitude.value * DEG_TO_RAD;
+
+    let elevation_rad = (latitude_rad.sin() * declination_rad.sin()
+        + latitude_rad.cos() * declination_rad.cos() * hour_angle_rad.cos())
+    .asin();
+    let elevation_rad = elevation_rad - parallax_correction_rad() * elevation_rad.cos();
+
+    let azimuth_rad = (-hour_angle_rad.sin()).atan2(
+        declination_rad.tan() * latitude_rad.cos() - latitude_rad.sin() * hour_angle_rad.cos(),
+    );
+
+    SolarPosition {
+        azimuth: (azimuth_rad / DEG_TO_RAD).rem_euclid(360.0),
+        elevation: elevation_rad / DEG_TO_RAD,
+    }
+}
+
+/// Outcome of resolving sunrise/sunset for a given day and location.
+///
+/// At latitudes inside the polar circles there can be days where the sun
+/// never rises or never sets; `Normal` is the common case everywhere else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SunriseAndSet {
+    Normal {
+        sunrise: DateTime<FixedOffset>,
+        sunset: DateTime<FixedOffset>,
+    },
+    PolarDay,
+    PolarNight,
+}
+
+/// Approximate solar elevation angle, in degrees, at local solar noon on
+/// the day of `date` for the given coordinates. Positive means the sun is
+/// above the horizon. Only the day of the year is used; the time of day
+/// is ignored.
+///
+/// This is a simplified calculation sufficient to classify polar day/night;
+/// `solar_position` is the full azimuth/elevation algorithm for an
+/// arbitrary instant.
+pub fn approximate_elevation(date: DateTime<FixedOffset>, coordinates: &Coordinates) -> f64 {
+    let day_of_year = date.ordinal() as f64;
+    let latitude_rad = coordinates.latitude.value * DEG_TO_RAD;
+
+    // Solar declination (degrees), standard approximation.
+    let declination_rad = 23.44 * DEG_TO_RAD * (360.0 / 365.0 * (day_of_year - 81.0) * DEG_TO_RAD).sin();
+
+    // Elevation at local solar noon, when the hour angle is zero.
+    let elevation_rad = (latitude_rad.sin() * declination_rad.sin()
+        + latitude_rad.cos() * declination_rad.cos())
+    .asin();
+
+    elevation_rad / DEG_TO_RAD
+}
+
+/// Classify a day as a normal sunrise/sunset day, polar day (sun never
+/// sets) or polar night (sun never rises), using the event times when
+/// present and falling back to the solar noon elevation when they're not.
+pub fn classify_day(
+    sunrise: &heliocron::structs::EventTime,
+    sunset: &heliocron::structs::EventTime,
+    solar_noon: DateTime<FixedOffset>,
+    coordinates: &Coordinates,
+) -> SunriseAndSet {
+    match (sunrise.datetime, sunset.datetime) {
+        (Some(sunrise), Some(sunset)) => SunriseAndSet::Normal { sunrise, sunset },
+        _ => {
+            if approximate_elevation(solar_noon, coordinates) > 0.0 {
+                SunriseAndSet::PolarDay
+            } else {
+                SunriseAndSet::PolarNight
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    fn svalbard() -> Coordinates {
+        // Longyearbyen, Svalbard: 78.2232° N, well inside the Arctic Circle.
+        Coordinates {
+            latitude: heliocron::structs::Latitude { value: 78.2232 },
+            longitude: heliocron::structs::Longitude { value: 15.6267 },
+        }
+    }
+
+    #[test]
+    fn test_approximate_elevation_polar_night_at_winter_solstice() {
+        let solar_noon = FixedOffset::east(0)
+            .ymd(2022, 12, 21)
+            .and_hms(12, 0, 0);
+        assert!(approximate_elevation(solar_noon, &svalbard()) < 0.0);
+    }
+
+    #[test]
+    fn test_approximate_elevation_polar_day_at_summer_solstice() {
+        let solar_noon = FixedOffset::east(0)
+            .ymd(2022, 6, 21)
+            .and_hms(12, 0, 0);
+        assert!(approximate_elevation(solar_noon, &svalbard()) > 0.0);
+    }
+
+    #[test]
+    fn test_solar_position_at_solar_noon_faces_roughly_south() {
+        // NYC 40.7128° N, 74.0060° W, 2022-01-24 local solar noon.
+        let date = FixedOffset::west(5 * 3600).ymd(2022, 1, 24).and_hms(12, 0, 0);
+        let nyc = Coordinates {
+            latitude: heliocron::structs::Latitude { value: 40.7128 },
+            longitude: heliocron::structs::Longitude { value: -74.0060 },
+        };
+        let position = solar_position(date, &nyc);
+        // The sun is above the horizon at solar noon in January, and culminates
+        // close to due south (180°) as seen from the northern hemisphere.
+        assert!(position.elevation > 0.0 && position.elevation < 90.0);
+        assert!((position.azimuth - 180.0).abs() < 30.0);
+    }
+}